@@ -5,25 +5,52 @@
 use notify::Watcher as NotifyWatcher;
 use slug::slugify;
 use std::path::{Path, PathBuf};
-use std::sync::mpsc;
-use std::time::SystemTime;
+use std::sync::atomic::{AtomicU64, AtomicUsize, Ordering};
+use std::sync::{mpsc, Arc, Mutex};
+use std::time::{Duration, SystemTime};
 use thiserror::Error;
 
 #[doc(inline)]
 pub use libloading::{self, Library, Symbol};
 
 /// Watches and re-builds the library upon changes to its source code.
+///
+/// In addition to watching for source changes, a `Watch` owns the currently loaded library (if
+/// any) and hands out symbols from it only via [`SymbolGuard`]s. This makes the "don't call a
+/// function from a library you're about to unload" rule an enforced invariant rather than a
+/// documentation footnote: [`Watch::reload`] waits for every outstanding `SymbolGuard` to be
+/// dropped before swapping in the newly built library.
 pub struct Watch {
     package_info: PackageInfo,
     _watcher: notify::RecommendedWatcher,
     event_rx: mpsc::Receiver<notify::Result<notify::Event>>,
+    current: Mutex<Option<Arc<Loaded>>>,
+}
+
+// The currently loaded library, along with a count of outstanding `SymbolGuard` borrows.
+struct Loaded {
+    lib: TempLibrary,
+    borrows: AtomicUsize,
 }
 
 struct PackageInfo {
     manifest_path: PathBuf,
     src_path: PathBuf,
     lib_name: String,
-    target_dir_path: PathBuf,
+    build_config: BuildConfig,
+    // The dylib path discovered by the most recent build, used to short-circuit a rebuild when
+    // nothing under `src_path` or `manifest_path` has changed since.
+    last_dylib_path: Mutex<Option<PathBuf>>,
+}
+
+impl PackageInfo {
+    fn last_dylib_path(&self) -> Option<PathBuf> {
+        self.last_dylib_path.lock().unwrap().clone()
+    }
+
+    fn set_last_dylib_path(&self, dylib_path: PathBuf) {
+        *self.last_dylib_path.lock().unwrap() = Some(dylib_path);
+    }
 }
 
 /// The information required to build the package's dylib target.
@@ -31,15 +58,186 @@ pub struct Package<'a> {
     info: &'a PackageInfo,
 }
 
+/// The default settle window used to coalesce a burst of filesystem events into a single
+/// rebuild. See [`BuildConfig::debounce`].
+pub const DEFAULT_DEBOUNCE: Duration = Duration::from_millis(200);
+
+/// Configuration for the `cargo build` invocation used to produce the dylib, along with how
+/// [`Watch`] reacts to filesystem events.
+///
+/// Constructed via the builder methods and passed to [`watch_with_config`].
+#[derive(Clone, Debug)]
+pub struct BuildConfig {
+    profile: Profile,
+    features: Vec<String>,
+    no_default_features: bool,
+    target: Option<String>,
+    extra_args: Vec<String>,
+    debounce: Duration,
+    ignore_unrelated_changes: bool,
+    search_paths: Vec<PathBuf>,
+}
+
+/// The cargo build profile to use when building the watched dylib.
+#[derive(Clone, Debug, Default)]
+pub enum Profile {
+    /// Build with `cargo build --release`.
+    #[default]
+    Release,
+    /// Build with `cargo build`, i.e. the `dev` profile.
+    Dev,
+    /// Build with `cargo build --profile <name>`.
+    Custom(String),
+}
+
+impl BuildConfig {
+    /// The default configuration, equivalent to a plain `cargo build --release`.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Set the build profile. Defaults to [`Profile::Release`].
+    pub fn profile(mut self, profile: Profile) -> Self {
+        self.profile = profile;
+        self
+    }
+
+    /// Enable the given feature, in addition to any already enabled.
+    pub fn feature(mut self, feature: impl Into<String>) -> Self {
+        self.features.push(feature.into());
+        self
+    }
+
+    /// Enable the given features, in addition to any already enabled.
+    pub fn features(mut self, features: impl IntoIterator<Item = impl Into<String>>) -> Self {
+        self.features.extend(features.into_iter().map(Into::into));
+        self
+    }
+
+    /// Disable the package's default features. Maps to `--no-default-features`.
+    pub fn no_default_features(mut self, no_default_features: bool) -> Self {
+        self.no_default_features = no_default_features;
+        self
+    }
+
+    /// Cross-compile for the given target triple. Maps to `--target <target>`.
+    pub fn target(mut self, target: impl Into<String>) -> Self {
+        self.target = Some(target.into());
+        self
+    }
+
+    /// Append an extra, raw argument to the `cargo build` invocation.
+    pub fn extra_arg(mut self, arg: impl Into<String>) -> Self {
+        self.extra_args.push(arg.into());
+        self
+    }
+
+    /// Append extra, raw arguments to the `cargo build` invocation.
+    pub fn extra_args(mut self, args: impl IntoIterator<Item = impl Into<String>>) -> Self {
+        self.extra_args.extend(args.into_iter().map(Into::into));
+        self
+    }
+
+    /// The window of time to keep coalescing filesystem events before triggering a rebuild.
+    ///
+    /// A single editor save (or a multi-file find/replace) often emits several filesystem events
+    /// in quick succession. Rather than rebuilding on the very first one, [`Watch::next`] waits
+    /// for this much time to pass with no further relevant events (see
+    /// [`BuildConfig::ignore_unrelated_changes`]) before returning. Defaults to
+    /// [`DEFAULT_DEBOUNCE`].
+    pub fn debounce(mut self, debounce: Duration) -> Self {
+        self.debounce = debounce;
+        self
+    }
+
+    /// Whether to ignore filesystem events that don't touch a relevant source file, i.e. events
+    /// under a `target` directory, hidden/temp files, or files other than `*.rs`/`Cargo.toml`.
+    ///
+    /// Defaults to `true`. Disable this if the watched package relies on build inputs with other
+    /// extensions (e.g. a build script reading some other file type).
+    pub fn ignore_unrelated_changes(mut self, ignore_unrelated_changes: bool) -> Self {
+        self.ignore_unrelated_changes = ignore_unrelated_changes;
+        self
+    }
+
+    /// Add a directory to the dynamic loader's search path used when loading the built dylib.
+    ///
+    /// This is useful when the watched library itself links against other shared objects (a
+    /// sibling dylib, a vendored native lib) that don't live on the system's default loader
+    /// search path.
+    ///
+    /// On Unix this works by `dlopen`-ing, with `RTLD_GLOBAL`, every shared library found
+    /// directly within `path` before loading the dylib, as the dynamic linker reads
+    /// `LD_LIBRARY_PATH`/`DYLD_LIBRARY_PATH` only at process start, so mutating them later has no
+    /// effect. See [`Build::load`]'s safety section for the side effects. On Windows, `path` is
+    /// prepended to the process' `PATH` instead.
+    pub fn search_path(mut self, path: impl Into<PathBuf>) -> Self {
+        self.search_paths.push(path.into());
+        self
+    }
+
+    /// Add directories to the dynamic loader's search path used when loading the built dylib.
+    ///
+    /// See [`BuildConfig::search_path`].
+    pub fn search_paths(mut self, paths: impl IntoIterator<Item = impl Into<PathBuf>>) -> Self {
+        self.search_paths.extend(paths.into_iter().map(Into::into));
+        self
+    }
+
+    // Apply this configuration's flags to the given `cargo build` command.
+    fn apply_to(&self, cmd: &mut std::process::Command) {
+        match &self.profile {
+            Profile::Release => {
+                cmd.arg("--release");
+            }
+            Profile::Dev => (),
+            Profile::Custom(name) => {
+                cmd.arg("--profile").arg(name);
+            }
+        }
+        if self.no_default_features {
+            cmd.arg("--no-default-features");
+        }
+        if !self.features.is_empty() {
+            cmd.arg("--features").arg(self.features.join(" "));
+        }
+        if let Some(target) = &self.target {
+            cmd.arg("--target").arg(target);
+        }
+        cmd.args(&self.extra_args);
+    }
+}
+
+impl Default for BuildConfig {
+    fn default() -> Self {
+        BuildConfig {
+            profile: Profile::default(),
+            features: Vec::new(),
+            no_default_features: false,
+            target: None,
+            extra_args: Vec::new(),
+            debounce: DEFAULT_DEBOUNCE,
+            ignore_unrelated_changes: true,
+            search_paths: Vec::new(),
+        }
+    }
+}
+
 /// The result of building a package's dynamic library.
 ///
 /// This can be used to load the dynamic library either in place or via a temporary file as to allow
 /// for re-building the package while using the library.
-pub struct Build<'a> {
-    lib_name: &'a str,
-    target_dir_path: &'a Path,
+pub struct Build {
+    dylib_path: PathBuf,
     timestamp: SystemTime,
     output: std::process::Output,
+    search_paths: Vec<PathBuf>,
+    up_to_date: bool,
+    // A process-unique id, used only to keep `tmp_file_stem` (and so `tmp_dylib_path`) from
+    // colliding across distinct `Build`s that happen to share a `timestamp` -- notably, repeated
+    // up-to-date builds of an unchanged dylib (see `Package::build`), which would otherwise alias
+    // the same temp file and so the same `TempLibrary::drop` removing it out from under another.
+    id: u64,
 }
 
 /// A wrapper around a `libloading::Library` that cleans up the library on `Drop`.
@@ -94,6 +292,13 @@ pub enum BuildError {
         #[from]
         err: ExitStatusUnsuccessfulError,
     },
+    #[error("an error occurred when attempting to read a cargo build message as json: {err}")]
+    Json {
+        #[from]
+        err: serde_json::Error,
+    },
+    #[error("cargo build succeeded but no `compiler-artifact` message for the `{lib_name}` dylib target was found")]
+    NoDylibArtifact { lib_name: String },
 }
 
 /// A process' output indicates unsuccessful completion.
@@ -131,6 +336,32 @@ pub enum LoadError {
     },
 }
 
+/// Errors that might occur while reloading the currently loaded library.
+#[derive(Debug, Error)]
+pub enum ReloadError {
+    #[error("{err}")]
+    Load {
+        #[from]
+        err: LoadError,
+    },
+    #[error(
+        "would block: {outstanding} outstanding `SymbolGuard`(s) must be dropped before reloading"
+    )]
+    WouldBlock { outstanding: usize },
+}
+
+/// Errors that might occur while retrieving a symbol from the currently loaded library.
+#[derive(Debug, Error)]
+pub enum SymbolError {
+    #[error("no library has been loaded yet")]
+    NotLoaded,
+    #[error("failed to load symbol with libloading: {err}")]
+    Library {
+        #[from]
+        err: libloading::Error,
+    },
+}
+
 impl ExitStatusUnsuccessfulError {
     /// Produces the error if output indicates failure.
     pub fn from_output(output: &std::process::Output) -> Option<Self> {
@@ -153,7 +384,16 @@ impl ExitStatusUnsuccessfulError {
 /// within the package.
 ///
 /// The `notify` crate is used to watch for file-system events in a cross-platform manner.
+///
+/// This is equivalent to calling [`watch_with_config`] with the default [`BuildConfig`], i.e. a
+/// plain `cargo build --release`.
 pub fn watch(path: &Path) -> Result<Watch, WatchError> {
+    watch_with_config(path, BuildConfig::default())
+}
+
+/// The same as [`watch`], but allows specifying the profile, features, target triple and other
+/// `cargo build` options used each time the library is re-built.
+pub fn watch_with_config(path: &Path, build_config: BuildConfig) -> Result<Watch, WatchError> {
     if !path.ends_with("Cargo.toml") && !path.ends_with("cargo.toml") {
         return Err(WatchError::InvalidPath);
     }
@@ -176,14 +416,10 @@ pub fn watch(path: &Path) -> Result<Watch, WatchError> {
     // Read the stdout as JSON.
     let json: serde_json::Value = serde_json::from_slice(&output.stdout)?;
 
-    // A function to read paths and name out of JSON.
-    let read_json = |json: &serde_json::Value| -> Option<(PathBuf, PathBuf, String)> {
+    // A function to read the src path and lib name out of JSON.
+    let read_json = |json: &serde_json::Value| -> Option<(PathBuf, String)> {
         let obj = json.as_object()?;
 
-        // Retrieve the target directory.
-        let target_dir_str = obj.get("target_directory")?.as_str()?;
-        let target_dir_path = Path::new(target_dir_str).to_path_buf();
-
         // Retrieve the first package as an object.
         let pkgs = obj.get("packages")?.as_array()?;
 
@@ -212,11 +448,10 @@ pub fn watch(path: &Path) -> Result<Watch, WatchError> {
         let src_root_str = target.get("src_path")?.as_str()?;
         let src_root_path = Path::new(src_root_str).to_path_buf();
 
-        Some((target_dir_path, src_root_path, lib_name))
+        Some((src_root_path, lib_name))
     };
 
-    let (target_dir_path, src_root_path, lib_name) =
-        read_json(&json).ok_or(WatchError::NoDylibTarget)?;
+    let (src_root_path, lib_name) = read_json(&json).ok_or(WatchError::NoDylibTarget)?;
     let src_dir_path = src_root_path
         .parent()
         .expect("src root has no parent directory");
@@ -228,6 +463,15 @@ pub fn watch(path: &Path) -> Result<Watch, WatchError> {
     })?;
     watcher.watch(src_dir_path, notify::RecursiveMode::Recursive)?;
 
+    // Also (non-recursively) watch the directory containing the manifest itself, so that edits
+    // to `Cargo.toml` (e.g. adding a dependency or feature) produce an event too -- without this,
+    // `is_relevant_path`'s `Cargo.toml` case could never be reached, since `src_dir_path` above
+    // doesn't contain the manifest in every layout.
+    let manifest_dir_path = path.parent().expect("manifest path has no parent directory");
+    if manifest_dir_path != src_dir_path {
+        watcher.watch(manifest_dir_path, notify::RecursiveMode::NonRecursive)?;
+    }
+
     // Collect the paths.
     let manifest_path = path.to_path_buf();
     let src_path = src_dir_path.to_path_buf();
@@ -237,13 +481,15 @@ pub fn watch(path: &Path) -> Result<Watch, WatchError> {
         manifest_path,
         src_path,
         lib_name,
-        target_dir_path,
+        build_config,
+        last_dylib_path: Mutex::new(None),
     };
 
     Ok(Watch {
         package_info,
         _watcher: watcher,
         event_rx,
+        current: Mutex::new(None),
     })
 }
 
@@ -259,22 +505,39 @@ impl Watch {
     }
 
     /// Wait for the library to be re-built after some change.
+    ///
+    /// To avoid triggering a rebuild storm from a single editor save (which often emits several
+    /// filesystem events in quick succession), the first relevant event received does not return
+    /// immediately. Instead, further events are drained until the configured debounce window
+    /// (see [`BuildConfig::debounce`]) passes without a new one arriving. Events that don't touch
+    /// a relevant source file are ignored entirely (see [`BuildConfig::ignore_unrelated_changes`]).
     pub fn next(&self) -> Result<Package, NextError> {
         loop {
-            let _event = match self.event_rx.recv() {
+            let event = match self.event_rx.recv() {
                 Err(_) => return Err(NextError::ChannelClosed),
-                Ok(event) => event,
+                Ok(event) => event?,
             };
+            if !self.is_relevant(&event) {
+                continue;
+            }
+            self.settle()?;
             return Ok(self.package());
         }
     }
 
     /// The same as `next`, but returns early if there are no pending events.
     pub fn try_next(&self) -> Result<Option<Package>, NextError> {
-        match self.event_rx.try_recv() {
-            Ok(_event) => return Ok(Some(self.package())),
-            Err(mpsc::TryRecvError::Disconnected) => Err(NextError::ChannelClosed),
-            Err(mpsc::TryRecvError::Empty) => Ok(None),
+        loop {
+            let event = match self.event_rx.try_recv() {
+                Ok(event) => event?,
+                Err(mpsc::TryRecvError::Disconnected) => return Err(NextError::ChannelClosed),
+                Err(mpsc::TryRecvError::Empty) => return Ok(None),
+            };
+            if !self.is_relevant(&event) {
+                continue;
+            }
+            self.settle()?;
+            return Ok(Some(self.package()));
         }
     }
 
@@ -285,6 +548,248 @@ impl Watch {
         let info = &self.package_info;
         Package { info }
     }
+
+    // Having received at least one relevant event, keep draining the channel until the
+    // configured debounce window passes with no further *relevant* events, coalescing a burst of
+    // filesystem events (e.g. from a single editor save) into a single rebuild. An irrelevant
+    // event (see `is_relevant`) is drained but doesn't itself extend the debounce window, so a
+    // stream of unrelated changes can't indefinitely postpone a real rebuild.
+    fn settle(&self) -> Result<(), NextError> {
+        let debounce = self.package_info.build_config.debounce;
+        let mut deadline = std::time::Instant::now() + debounce;
+        loop {
+            let remaining = deadline.saturating_duration_since(std::time::Instant::now());
+            if remaining.is_zero() {
+                return Ok(());
+            }
+            match self.event_rx.recv_timeout(remaining) {
+                Ok(event) => {
+                    let event = event?;
+                    if self.is_relevant(&event) {
+                        deadline = std::time::Instant::now() + debounce;
+                    }
+                }
+                Err(mpsc::RecvTimeoutError::Timeout) => return Ok(()),
+                Err(mpsc::RecvTimeoutError::Disconnected) => return Err(NextError::ChannelClosed),
+            }
+        }
+    }
+
+    // Whether the given event touches a path that should trigger a rebuild.
+    fn is_relevant(&self, event: &notify::Event) -> bool {
+        if !self.package_info.build_config.ignore_unrelated_changes {
+            return true;
+        }
+        event.paths.iter().any(|p| is_relevant_path(p))
+    }
+
+    /// Load the given `Build`, making it the currently active library and replacing any
+    /// previously loaded one.
+    ///
+    /// If any [`SymbolGuard`] borrowed from the previously loaded library is still alive, this
+    /// blocks, polling until every one of them has been dropped, before swapping in the new
+    /// library. This ensures the file backing the old library is never removed while code
+    /// borrowed from it might still be running. See [`Watch::try_reload`] for a non-blocking
+    /// variant.
+    ///
+    /// # Safety
+    ///
+    /// See [`Build::load`].
+    pub unsafe fn reload(&self, build: Build) -> Result<(), ReloadError> {
+        self.wait_for_borrows();
+        self.set_current(unsafe { build.load() }?);
+        Ok(())
+    }
+
+    /// The same as [`Watch::reload`], but additionally attempts to migrate live state from the
+    /// currently loaded library into the newly built one via the optional
+    /// `__hotlib_serialize_state` / `__hotlib_deserialize_state` protocol (see
+    /// [`SERIALIZE_STATE_SYMBOL`] and [`DESERIALIZE_STATE_SYMBOL`]).
+    ///
+    /// If a library is currently loaded and exports `__hotlib_serialize_state`, and the newly
+    /// built library exports `__hotlib_deserialize_state`, the old library's state is captured
+    /// and fed into the new one before the old library is dropped. If either symbol is missing
+    /// (or no library is currently loaded), this reloads exactly as [`Watch::reload`] would --
+    /// the protocol is entirely opt-in.
+    ///
+    /// # Safety
+    ///
+    /// See [`Build::load`], [`TempLibrary::serialize_state`] and
+    /// [`TempLibrary::deserialize_state`].
+    pub unsafe fn reload_with_state_transfer(&self, build: Build) -> Result<(), ReloadError> {
+        self.wait_for_borrows();
+        let old = self.current.lock().unwrap().clone();
+        let state = match &old {
+            Some(old) => unsafe { old.lib.serialize_state() },
+            None => None,
+        };
+        let new_lib = unsafe { build.load() }?;
+        if let Some(state) = state {
+            unsafe { new_lib.deserialize_state(state) };
+        }
+        self.set_current(new_lib);
+        Ok(())
+    }
+
+    /// The same as [`Watch::reload`], but returns [`ReloadError::WouldBlock`] immediately rather
+    /// than waiting if any [`SymbolGuard`] borrowed from the currently loaded library is still
+    /// alive.
+    ///
+    /// # Safety
+    ///
+    /// See [`Build::load`].
+    pub unsafe fn try_reload(&self, build: Build) -> Result<(), ReloadError> {
+        let outstanding = self.outstanding_borrows();
+        if outstanding > 0 {
+            return Err(ReloadError::WouldBlock { outstanding });
+        }
+        self.set_current(unsafe { build.load() }?);
+        Ok(())
+    }
+
+    /// Borrow a symbol by name from the currently loaded library.
+    ///
+    /// The returned [`SymbolGuard`] counts as an outstanding borrow for as long as it's alive;
+    /// [`Watch::reload`] will wait for it (and any other outstanding guards) to be dropped before
+    /// swapping in a newly built library.
+    ///
+    /// # Safety
+    ///
+    /// See `libloading::Library::get`.
+    pub unsafe fn symbol<T: 'static>(&self, name: &[u8]) -> Result<SymbolGuard<T>, SymbolError> {
+        let loaded = self
+            .current
+            .lock()
+            .unwrap()
+            .clone()
+            .ok_or(SymbolError::NotLoaded)?;
+        loaded.borrows.fetch_add(1, Ordering::SeqCst);
+        let symbol = match unsafe { loaded.lib.lib().get::<T>(name) } {
+            Ok(symbol) => symbol,
+            Err(err) => {
+                loaded.borrows.fetch_sub(1, Ordering::SeqCst);
+                return Err(SymbolError::from(err));
+            }
+        };
+        // Safety: `SymbolGuard` carries its own `Arc<Loaded>` clone, which keeps the `Library`
+        // this symbol was loaded from alive for at least as long as the guard itself, so
+        // extending the symbol's lifetime to match the guard's is sound.
+        let symbol = unsafe {
+            std::mem::transmute::<libloading::Symbol<T>, libloading::Symbol<'static, T>>(symbol)
+        };
+        Ok(SymbolGuard { symbol, loaded })
+    }
+
+    // The number of `SymbolGuard`s currently borrowed from the active library, or `0` if no
+    // library has been loaded yet.
+    fn outstanding_borrows(&self) -> usize {
+        self.current
+            .lock()
+            .unwrap()
+            .as_ref()
+            .map(|loaded| loaded.borrows.load(Ordering::SeqCst))
+            .unwrap_or(0)
+    }
+
+    // Block, polling at `RELOAD_POLL_INTERVAL`, until every outstanding `SymbolGuard` borrowed
+    // from the currently loaded library has been dropped.
+    fn wait_for_borrows(&self) {
+        while self.outstanding_borrows() > 0 {
+            std::thread::sleep(RELOAD_POLL_INTERVAL);
+        }
+    }
+
+    // Make the given freshly loaded library the currently active one.
+    fn set_current(&self, lib: TempLibrary) {
+        let loaded = Loaded {
+            lib,
+            borrows: AtomicUsize::new(0),
+        };
+        *self.current.lock().unwrap() = Some(Arc::new(loaded));
+    }
+}
+
+/// The interval at which [`Watch::reload`] polls for outstanding [`SymbolGuard`]s to be dropped.
+pub const RELOAD_POLL_INTERVAL: Duration = Duration::from_millis(10);
+
+/// An RAII guard around a symbol borrowed from the library currently loaded by a [`Watch`].
+///
+/// While any `SymbolGuard` is alive, [`Watch::reload`] will wait for it to be dropped (and
+/// [`Watch::try_reload`] will return [`ReloadError::WouldBlock`]) before swapping in a newly
+/// built library, so the dylib backing this symbol is never removed out from under it.
+pub struct SymbolGuard<T: 'static> {
+    symbol: libloading::Symbol<'static, T>,
+    loaded: Arc<Loaded>,
+}
+
+impl<T> std::ops::Deref for SymbolGuard<T> {
+    type Target = T;
+    fn deref(&self) -> &T {
+        &self.symbol
+    }
+}
+
+impl<T> Drop for SymbolGuard<T> {
+    fn drop(&mut self) {
+        self.loaded.borrows.fetch_sub(1, Ordering::SeqCst);
+    }
+}
+
+// Whether a changed path is relevant to rebuilding the watched package, used to filter out
+// noise like `target/` build output, hidden/temp files and non-source changes.
+fn is_relevant_path(path: &Path) -> bool {
+    if path.components().any(|c| c.as_os_str() == "target") {
+        return false;
+    }
+    let file_name = match path.file_name().and_then(|n| n.to_str()) {
+        Some(file_name) => file_name,
+        None => return false,
+    };
+    if file_name.starts_with('.') || file_name.ends_with('~') {
+        return false;
+    }
+    if file_name == "Cargo.toml" {
+        return true;
+    }
+    path.extension().and_then(|e| e.to_str()) == Some("rs")
+}
+
+#[cfg(test)]
+mod is_relevant_path_tests {
+    use super::is_relevant_path;
+    use std::path::Path;
+
+    #[test]
+    fn rust_source_file_is_relevant() {
+        assert!(is_relevant_path(Path::new("src/lib.rs")));
+    }
+
+    #[test]
+    fn manifest_is_relevant() {
+        assert!(is_relevant_path(Path::new("Cargo.toml")));
+    }
+
+    #[test]
+    fn build_output_under_target_dir_is_not_relevant() {
+        assert!(!is_relevant_path(Path::new(
+            "target/debug/build/foo/output.rs"
+        )));
+    }
+
+    #[test]
+    fn hidden_file_is_not_relevant() {
+        assert!(!is_relevant_path(Path::new("src/.lib.rs.swp")));
+    }
+
+    #[test]
+    fn editor_backup_file_is_not_relevant() {
+        assert!(!is_relevant_path(Path::new("src/lib.rs~")));
+    }
+
+    #[test]
+    fn non_rust_non_manifest_file_is_not_relevant() {
+        assert!(!is_relevant_path(Path::new("README.md")));
+    }
 }
 
 impl<'a> Package<'a> {
@@ -299,66 +804,273 @@ impl<'a> Package<'a> {
     }
 
     /// Builds the package's dynamic library target.
-    pub fn build(&self) -> Result<Build<'a>, BuildError> {
+    ///
+    /// If the dylib produced by a previous call to this method is newer than every file under
+    /// [`Package::src_path`] and the package's `Cargo.toml`, cargo is skipped entirely and the
+    /// existing artifact is reused -- see [`Build::is_up_to_date`].
+    pub fn build(&self) -> Result<Build, BuildError> {
         let PackageInfo {
             ref manifest_path,
             ref lib_name,
-            ref target_dir_path,
+            ref build_config,
             ..
         } = self.info;
 
-        // Tell cargo to compile the package.
+        if let Some(dylib_path) = self.info.last_dylib_path() {
+            if is_up_to_date(self.info, &dylib_path)? {
+                let timestamp = std::fs::metadata(&dylib_path)?.modified()?;
+                return Ok(Build {
+                    dylib_path,
+                    timestamp,
+                    output: up_to_date_output(),
+                    search_paths: build_config.search_paths.clone(),
+                    up_to_date: true,
+                    id: next_build_id(),
+                });
+            }
+        }
+
+        // Tell cargo to compile the package, asking it to stream the artifacts it produces as
+        // newline-delimited JSON so that we can discover the authoritative dylib path rather
+        // than guessing at the output filename ourselves.
         let manifest_path_str = format!("{}", manifest_path.display());
-        let output = std::process::Command::new("cargo")
-            .arg("build")
+        let mut cmd = std::process::Command::new("cargo");
+        cmd.arg("build")
             .arg("--manifest-path")
             .arg(&manifest_path_str)
             .arg("--lib")
-            .arg("--release")
-            .output()?;
+            .arg("--message-format=json-render-diagnostics");
+        build_config.apply_to(&mut cmd);
+        let mut child = cmd
+            .stdout(std::process::Stdio::piped())
+            .stderr(std::process::Stdio::piped())
+            .spawn()?;
+
+        // Drain stderr on a separate thread, concurrently with reading stdout below. cargo can
+        // write enough human-readable diagnostics to stderr (e.g. warnings across a large
+        // workspace) to fill the OS pipe buffer; if we only read stdout, cargo blocks on that
+        // stderr write and the build hangs forever.
+        let child_stderr = child.stderr.take().expect("child stderr was not captured");
+        let stderr_thread = std::thread::spawn(move || -> std::io::Result<Vec<u8>> {
+            let mut child_stderr = child_stderr;
+            let mut stderr = Vec::new();
+            std::io::Read::read_to_end(&mut child_stderr, &mut stderr)?;
+            Ok(stderr)
+        });
+
+        // Read cargo's stdout incrementally, one JSON message per line, looking for the
+        // `compiler-artifact` message describing the dylib we're after. We keep the raw lines
+        // around too, so that `cargo_output` can still expose the full stream of diagnostics.
+        let child_stdout = child.stdout.take().expect("child stdout was not captured");
+        let mut stdout = Vec::new();
+        let mut dylib_path = None;
+        for line in std::io::BufRead::lines(std::io::BufReader::new(child_stdout)) {
+            let line = line?;
+            let msg: serde_json::Value = serde_json::from_str(&line)?;
+            if let Some(path) = dylib_path_from_artifact_message(&msg, lib_name) {
+                dylib_path = Some(path);
+            }
+            stdout.extend_from_slice(line.as_bytes());
+            stdout.push(b'\n');
+        }
+
+        // Wait for cargo to finish and join the stderr-draining thread to build the full `Output`.
+        let status = child.wait()?;
+        let stderr = stderr_thread
+            .join()
+            .expect("stderr reader thread panicked")?;
+        let output = std::process::Output {
+            status,
+            stdout,
+            stderr,
+        };
 
         // Check the exit status.
         if let Some(err) = ExitStatusUnsuccessfulError::from_output(&output) {
             return Err(BuildError::from(err));
         }
 
+        let dylib_path = dylib_path.ok_or_else(|| BuildError::NoDylibArtifact {
+            lib_name: lib_name.clone(),
+        })?;
+
         // Time stamp the moment of build completion.
         let timestamp = SystemTime::now();
 
+        self.info.set_last_dylib_path(dylib_path.clone());
+
         Ok(Build {
+            dylib_path,
             timestamp,
             output,
-            lib_name,
-            target_dir_path,
+            search_paths: build_config.search_paths.clone(),
+            up_to_date: false,
+            id: next_build_id(),
         })
     }
 }
 
-impl<'a> Build<'a> {
+// A monotonically increasing id, unique per `Build`, used to keep `Build::tmp_file_stem` from
+// colliding across distinct `Build`s.
+static NEXT_BUILD_ID: AtomicU64 = AtomicU64::new(0);
+
+fn next_build_id() -> u64 {
+    NEXT_BUILD_ID.fetch_add(1, Ordering::Relaxed)
+}
+
+// Whether the dylib at `dylib_path` is newer than every file under the package's `src_path` and
+// its `Cargo.toml`, i.e. whether a rebuild would be a no-op.
+fn is_up_to_date(info: &PackageInfo, dylib_path: &Path) -> Result<bool, std::io::Error> {
+    let dylib_mtime = match std::fs::metadata(dylib_path).and_then(|m| m.modified()) {
+        Ok(mtime) => mtime,
+        // The artifact doesn't exist (or its mtime can't be read) -- nothing to reuse.
+        Err(_) => return Ok(false),
+    };
+    let manifest_mtime = std::fs::metadata(&info.manifest_path)?.modified()?;
+    if manifest_mtime >= dylib_mtime {
+        return Ok(false);
+    }
+    match newest_mtime(&info.src_path)? {
+        Some(src_mtime) if src_mtime >= dylib_mtime => Ok(false),
+        _ => Ok(true),
+    }
+}
+
+// The most recent modification time found within `path`, recursing into directories.
+fn newest_mtime(path: &Path) -> Result<Option<SystemTime>, std::io::Error> {
+    let metadata = std::fs::metadata(path)?;
+    if !metadata.is_dir() {
+        return Ok(Some(metadata.modified()?));
+    }
+    let mut newest = None;
+    for entry in std::fs::read_dir(path)? {
+        if let Some(mtime) = newest_mtime(&entry?.path())? {
+            newest = Some(newest.map_or(mtime, |n: SystemTime| n.max(mtime)));
+        }
+    }
+    Ok(newest)
+}
+
+// A successful, empty `Output` used for an up-to-date `Build` that skipped invoking cargo.
+fn up_to_date_output() -> std::process::Output {
+    std::process::Output {
+        status: success_exit_status(),
+        stdout: Vec::new(),
+        stderr: Vec::new(),
+    }
+}
+
+#[cfg(unix)]
+fn success_exit_status() -> std::process::ExitStatus {
+    std::os::unix::process::ExitStatusExt::from_raw(0)
+}
+
+#[cfg(windows)]
+fn success_exit_status() -> std::process::ExitStatus {
+    std::os::windows::process::ExitStatusExt::from_raw(0)
+}
+
+// Given a single `cargo build --message-format=json` message, return the dylib artifact path if
+// the message is a `compiler-artifact` for the package's dylib target.
+fn dylib_path_from_artifact_message(msg: &serde_json::Value, lib_name: &str) -> Option<PathBuf> {
+    let obj = msg.as_object()?;
+    if obj.get("reason")?.as_str()? != "compiler-artifact" {
+        return None;
+    }
+    let target = obj.get("target")?.as_object()?;
+    if target.get("name")?.as_str()? != lib_name {
+        return None;
+    }
+    let kind = target.get("kind")?.as_array()?;
+    let filenames = obj.get("filenames")?.as_array()?;
+    kind.iter()
+        .zip(filenames.iter())
+        .find(|(k, _)| k.as_str() == Some("dylib"))
+        .and_then(|(_, f)| f.as_str())
+        .map(|s| Path::new(s).to_path_buf())
+}
+
+#[cfg(test)]
+mod dylib_path_from_artifact_message_tests {
+    use super::dylib_path_from_artifact_message;
+    use serde_json::json;
+
+    #[test]
+    fn picks_dylib_filename_at_matching_kind_index() {
+        let msg = json!({
+            "reason": "compiler-artifact",
+            "target": {"name": "foo", "kind": ["lib", "dylib"]},
+            "filenames": ["/target/debug/libfoo.rlib", "/target/debug/libfoo.so"],
+        });
+        let path = dylib_path_from_artifact_message(&msg, "foo").unwrap();
+        assert_eq!(path, std::path::Path::new("/target/debug/libfoo.so"));
+    }
+
+    #[test]
+    fn ignores_non_compiler_artifact_messages() {
+        let msg = json!({
+            "reason": "build-finished",
+            "target": {"name": "foo", "kind": ["dylib"]},
+            "filenames": ["/target/debug/libfoo.so"],
+        });
+        assert!(dylib_path_from_artifact_message(&msg, "foo").is_none());
+    }
+
+    #[test]
+    fn ignores_artifacts_for_other_targets() {
+        let msg = json!({
+            "reason": "compiler-artifact",
+            "target": {"name": "bar", "kind": ["dylib"]},
+            "filenames": ["/target/debug/libbar.so"],
+        });
+        assert!(dylib_path_from_artifact_message(&msg, "foo").is_none());
+    }
+
+    #[test]
+    fn ignores_artifacts_with_no_dylib_kind() {
+        let msg = json!({
+            "reason": "compiler-artifact",
+            "target": {"name": "foo", "kind": ["lib"]},
+            "filenames": ["/target/debug/libfoo.rlib"],
+        });
+        assert!(dylib_path_from_artifact_message(&msg, "foo").is_none());
+    }
+}
+
+impl Build {
     /// The output of the cargo process.
+    ///
+    /// For a `Build` that skipped invoking cargo (see [`Build::is_up_to_date`]), this is an
+    /// empty, successful `Output`.
     pub fn cargo_output(&self) -> &std::process::Output {
         &self.output
     }
 
+    /// Whether this `Build` reused an existing, up-to-date dylib without invoking cargo.
+    ///
+    /// This is the case when the dylib produced by a previous build is newer than every file
+    /// under the watched `src_path` and the package's `Cargo.toml`.
+    pub fn is_up_to_date(&self) -> bool {
+        self.up_to_date
+    }
+
     /// The moment at which the build was completed.
     pub fn timestamp(&self) -> SystemTime {
         self.timestamp
     }
 
-    /// The path to the generated dylib target.
-    pub fn dylib_path(&self) -> PathBuf {
-        let file_stem = self.file_stem();
-        self.target_dir_path
-            .join("release")
-            .join(file_stem)
-            .with_extension(dylib_ext())
+    /// The path to the generated dylib target, as reported by cargo itself.
+    pub fn dylib_path(&self) -> &Path {
+        &self.dylib_path
     }
 
     /// The path to the temporary dynamic library clone that will be created upon `load`.
     pub fn tmp_dylib_path(&self) -> PathBuf {
-        tmp_dir()
-            .join(self.tmp_file_stem())
-            .with_extension(dylib_ext())
+        let mut path = tmp_dir().join(self.tmp_file_stem());
+        if let Some(ext) = self.dylib_path.extension() {
+            path.set_extension(ext);
+        }
+        path
     }
 
     /// Copy the library to the platform's temporary directory and load it from there.
@@ -370,7 +1082,16 @@ impl<'a> Build<'a> {
     /// Loading dynamic libraries unfortunately appears to be inherently unsafe. See [this
     /// note](https://docs.rs/libloading/0.7.0/libloading/changelog/r0_7_0/index.html#loading-functions-are-now-unsafe)
     /// in the `libloading` documentation for an explanation.
+    ///
+    /// If [`BuildConfig::search_path`]/[`search_paths`](BuildConfig::search_paths) were used, this
+    /// also makes the dynamic loader aware of them first: on Unix this permanently loads (and
+    /// runs the initializers of) every shared library found directly within those directories
+    /// into the current process; on Windows this mutates the process-wide `PATH` environment
+    /// variable, so callers must ensure this doesn't race with other threads reading or writing
+    /// it. See [`apply_search_paths`] for why this is necessary.
     pub unsafe fn load(self) -> Result<TempLibrary, LoadError> {
+        unsafe { apply_search_paths(&self.search_paths) };
+
         let dylib_path = self.dylib_path();
         let tmp_path = self.tmp_dylib_path();
         let tmp_dir = tmp_path.parent().expect("temp dylib path has no parent");
@@ -415,30 +1136,35 @@ impl<'a> Build<'a> {
     ///
     /// Note that if you do this, you will have to ensure the returned `Library` is dropped before
     /// attempting to re-build the library.
+    ///
+    /// # Safety
+    ///
+    /// Loading dynamic libraries unfortunately appears to be inherently unsafe. See [this
+    /// note](https://docs.rs/libloading/0.7.0/libloading/changelog/r0_7_0/index.html#loading-functions-are-now-unsafe)
+    /// in the `libloading` documentation for an explanation.
+    ///
+    /// If [`BuildConfig::search_path`]/[`search_paths`](BuildConfig::search_paths) were used, this
+    /// also makes the dynamic loader aware of them first, with the same process-wide side effects
+    /// described in [`Build::load`]'s safety section.
     pub unsafe fn load_in_place(self) -> Result<libloading::Library, libloading::Error> {
-        let dylib_path = self.dylib_path();
-        libloading::Library::new(dylib_path)
-    }
-
-    // The file stem of the built dynamic library.
-    fn file_stem(&self) -> String {
-        // TODO: On windows, the generated lib does not contain the "lib" prefix.
-        // A proper solution would likely involve retrieving the file stem from cargo itself.
-        #[cfg(target_os = "windows")]
-        {
-            format!("{}", self.lib_name)
-        }
-        #[cfg(not(target_os = "windows"))]
-        {
-            format!("lib{}", self.lib_name)
-        }
+        unsafe { apply_search_paths(&self.search_paths) };
+        libloading::Library::new(&self.dylib_path)
     }
 
     // Produce the file stem for the temporary dynamic library clone that will be created upon
     // `load`.
     fn tmp_file_stem(&self) -> String {
+        let file_stem = self
+            .dylib_path
+            .file_stem()
+            .and_then(|s| s.to_str())
+            .expect("dylib path has no file stem");
         let timestamp_slug = slugify(format!("{}", humantime::format_rfc3339(self.timestamp)));
-        format!("{}-{}", self.file_stem(), timestamp_slug)
+        // `self.id` (rather than `timestamp_slug` alone) is what guarantees uniqueness here: an
+        // up-to-date `Build` (see `Package::build`) reuses the existing artifact's mtime as
+        // `timestamp`, so repeated up-to-date builds would otherwise produce identical stems and
+        // alias the same temp file across distinct `TempLibrary`s.
+        format!("{}-{}-{}", file_stem, timestamp_slug, self.id)
     }
 }
 
@@ -461,6 +1187,94 @@ impl TempLibrary {
     pub fn path(&self) -> &Path {
         &self.path
     }
+
+    /// Call the library's `__hotlib_serialize_state` export, if present, to capture its live
+    /// state ready to hand to another library's `__hotlib_deserialize_state` export (see
+    /// [`Watch::reload_with_state_transfer`]).
+    ///
+    /// Returns `None` if the library doesn't define [`SERIALIZE_STATE_SYMBOL`] -- the state
+    /// transfer protocol is entirely opt-in, so a library that doesn't implement it reloads as
+    /// if this were never called.
+    ///
+    /// # Safety
+    ///
+    /// If present, `__hotlib_serialize_state` must match [`SerializeStateFn`]'s signature and
+    /// return a [`StateBuffer`] produced by [`StateBuffer::from_vec`].
+    pub unsafe fn serialize_state(&self) -> Option<Vec<u8>> {
+        let serialize: libloading::Symbol<SerializeStateFn> =
+            unsafe { self.lib().get(SERIALIZE_STATE_SYMBOL) }.ok()?;
+        Some(unsafe { serialize().into_vec() })
+    }
+
+    /// Call the library's `__hotlib_deserialize_state` export, if present, handing it `state` as
+    /// captured by a previous call to [`TempLibrary::serialize_state`] (see
+    /// [`Watch::reload_with_state_transfer`]).
+    ///
+    /// Returns `false` if the library doesn't define [`DESERIALIZE_STATE_SYMBOL`] -- the state
+    /// transfer protocol is entirely opt-in, so a library that doesn't implement it reloads as
+    /// if this were never called.
+    ///
+    /// # Safety
+    ///
+    /// If present, `__hotlib_deserialize_state` must match [`DeserializeStateFn`]'s signature,
+    /// and `state` must have been produced by a compatible `__hotlib_serialize_state`
+    /// implementation.
+    pub unsafe fn deserialize_state(&self, state: Vec<u8>) -> bool {
+        let deserialize: libloading::Symbol<DeserializeStateFn> =
+            match unsafe { self.lib().get(DESERIALIZE_STATE_SYMBOL) } {
+                Ok(deserialize) => deserialize,
+                Err(_) => return false,
+            };
+        unsafe { deserialize(StateBuffer::from_vec(state)) };
+        true
+    }
+}
+
+/// The conventional name of an optional exported function a hot-reloaded library may define to
+/// capture its live state immediately before being reloaded. Expected signature:
+/// [`SerializeStateFn`]. See [`TempLibrary::serialize_state`].
+pub const SERIALIZE_STATE_SYMBOL: &[u8] = b"__hotlib_serialize_state";
+
+/// The conventional name of an optional exported function a hot-reloaded library may define to
+/// restore its live state immediately after being reloaded. Expected signature:
+/// [`DeserializeStateFn`]. See [`TempLibrary::deserialize_state`].
+pub const DESERIALIZE_STATE_SYMBOL: &[u8] = b"__hotlib_deserialize_state";
+
+/// The signature expected of a [`SERIALIZE_STATE_SYMBOL`] export.
+pub type SerializeStateFn = unsafe extern "C" fn() -> StateBuffer;
+
+/// The signature expected of a [`DESERIALIZE_STATE_SYMBOL`] export.
+pub type DeserializeStateFn = unsafe extern "C" fn(StateBuffer);
+
+/// An opaque, FFI-safe buffer of serialized state, passed by value across the old/new library
+/// boundary during [`Watch::reload_with_state_transfer`].
+#[repr(C)]
+pub struct StateBuffer {
+    ptr: *mut u8,
+    len: usize,
+}
+
+impl StateBuffer {
+    /// Construct a `StateBuffer` from a `Vec<u8>`, transferring ownership of its backing
+    /// allocation to whoever holds the buffer. Reconstruct it with [`StateBuffer::into_vec`].
+    pub fn from_vec(state: Vec<u8>) -> Self {
+        let boxed = state.into_boxed_slice();
+        let len = boxed.len();
+        let ptr = Box::into_raw(boxed) as *mut u8;
+        StateBuffer { ptr, len }
+    }
+
+    /// Reconstruct the `Vec<u8>` produced by [`StateBuffer::from_vec`], taking ownership of its
+    /// backing allocation.
+    ///
+    /// # Safety
+    ///
+    /// `self` must have been produced by [`StateBuffer::from_vec`], and must not be reconstructed
+    /// more than once.
+    pub unsafe fn into_vec(self) -> Vec<u8> {
+        let slice = unsafe { std::slice::from_raw_parts_mut(self.ptr, self.len) };
+        unsafe { Box::from_raw(slice as *mut [u8]) }.into_vec()
+    }
 }
 
 impl std::ops::Deref for TempLibrary {
@@ -482,29 +1296,76 @@ fn tmp_dir() -> PathBuf {
     std::env::temp_dir().join("hotlib")
 }
 
-// Get the dylib extension for this platform.
+// Make the dynamic loader aware of `paths` before a subsequent `libloading::Library::new` call,
+// so that it (including any lookups the loaded library itself makes in order to resolve its own
+// transitive dependencies) can find libraries that live there.
+//
+// # Safety
+//
+// On Unix this permanently loads every shared library found directly within `paths` into the
+// current process (see the comment on `preload_dependencies` for why), which runs their
+// initializers; on Windows this mutates the process-wide `PATH` environment variable, so callers
+// must ensure this doesn't race with other threads reading or writing it.
+#[cfg(unix)]
+unsafe fn apply_search_paths(paths: &[PathBuf]) {
+    unsafe { preload_dependencies(paths) };
+}
+
+#[cfg(windows)]
+unsafe fn apply_search_paths(paths: &[PathBuf]) {
+    if paths.is_empty() {
+        return;
+    }
+    let existing = std::env::var_os("PATH");
+    let all_paths = paths
+        .iter()
+        .cloned()
+        .chain(existing.iter().flat_map(std::env::split_paths));
+    if let Ok(joined) = std::env::join_paths(all_paths) {
+        std::env::set_var("PATH", joined);
+    }
+}
+
+// On Linux and macOS, simply `set_var`-ing `LD_LIBRARY_PATH`/`DYLD_LIBRARY_PATH` immediately
+// before loading the dylib does *not* help it resolve its own transitive dependencies: the
+// dynamic linker caches its library search path at process start and never re-consults the
+// environment for `dlopen`s (including the loader's own internal lookups) performed later in the
+// process's life. Windows' `LoadLibrary` doesn't have this problem -- it re-reads `PATH` on every
+// call -- so `apply_search_paths` only needs the simple env-var approach there.
 //
-// TODO: This should be exposed from cargo.
-fn dylib_ext() -> &'static str {
-    #[cfg(target_os = "linux")]
-    {
-        return "so";
-    }
-    #[cfg(any(target_os = "macos", target_os = "ios"))]
-    {
-        return "dylib";
-    }
-    #[cfg(target_os = "windows")]
-    {
-        return "dll";
-    }
-    #[cfg(not(any(
-        target_os = "linux",
-        target_os = "macos",
-        target_os = "ios",
-        target_os = "windows"
-    )))]
-    {
-        panic!("unknown dynamic library for this platform")
+// Instead, on Unix, we `dlopen` every shared library found directly within `paths` ourselves with
+// `RTLD_GLOBAL`. This makes their symbols globally visible within the process, so when the
+// dynamic linker then resolves the watched dylib's `DT_NEEDED` entries, it finds a match already
+// loaded rather than needing to search the filesystem for it at all.
+//
+// These libraries are intentionally never unloaded (`Library::open`'s guard is forgotten below):
+// unloading a dependency that's satisfied another library's `DT_NEEDED` entry risks breaking any
+// future reload that relies on the same dependency, and a hot-reloading process is expected to
+// keep the same set of native dependencies resident for its entire lifetime. Libraries that fail
+// to open (e.g. a non-library file, or one with its own unresolved dependencies) are silently
+// skipped on a best-effort basis.
+#[cfg(unix)]
+unsafe fn preload_dependencies(paths: &[PathBuf]) {
+    use libloading::os::unix::{Library, RTLD_GLOBAL, RTLD_NOW};
+
+    #[cfg(target_os = "macos")]
+    const DYLIB_EXTENSION: &str = "dylib";
+    #[cfg(not(target_os = "macos"))]
+    const DYLIB_EXTENSION: &str = "so";
+
+    for dir in paths {
+        let entries = match std::fs::read_dir(dir) {
+            Ok(entries) => entries,
+            Err(_) => continue,
+        };
+        for entry in entries.flatten() {
+            let path = entry.path();
+            if path.extension().and_then(|ext| ext.to_str()) != Some(DYLIB_EXTENSION) {
+                continue;
+            }
+            if let Ok(lib) = unsafe { Library::open(Some(&path), RTLD_NOW | RTLD_GLOBAL) } {
+                std::mem::forget(lib);
+            }
+        }
     }
 }